@@ -0,0 +1,276 @@
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::os::unix::io::FromRawFd;
+use std::time::Duration;
+use std::collections::HashMap;
+use std::mem;
+use std::ptr;
+
+use libc;
+
+use reactor::{Reactor, Notifier, ReadEventType, WriteEventType};
+use EventControl;
+
+const NOTIFY_TOKEN: u64 = 0;
+const TIMER_TOKEN_BASE: u64 = 1 << 32;
+const RECV_BUF_SIZE: usize = 64 * 1024;
+
+type TimerCallback = Box<dyn Fn() -> EventControl + Send>;
+
+/// Cross-thread wakeup handle for an `Epoll`, backed by a `dup`'d copy of
+/// its `eventfd` so it can wake a blocked `epoll_wait()` without touching
+/// any of the reactor's own state.
+pub struct EpollNotifier {
+    notify_fd: i32
+}
+
+unsafe impl Send for EpollNotifier {}
+unsafe impl Sync for EpollNotifier {}
+
+impl Notifier for EpollNotifier {
+    fn notify(&self) {
+        let value: u64 = 1;
+        unsafe {
+            libc::write(self.notify_fd, &value as *const u64 as *const libc::c_void, mem::size_of::<u64>());
+        }
+    }
+}
+
+impl Drop for EpollNotifier {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.notify_fd);
+        }
+    }
+}
+
+/// `Reactor` implementation for Linux built directly on `libc::epoll_*`.
+///
+/// Cross-thread wakeups (`notify()`) use an `eventfd` registered in the
+/// same epoll set rather than kqueue's `EVFILT_USER`, since epoll has no
+/// user-triggerable filter of its own.
+pub struct Epoll {
+    epfd: i32,
+    notify_fd: i32,
+    reads: HashMap<usize, ReadEventType>,
+    writes: HashMap<usize, WriteEventType>,
+    timers: HashMap<u64, (i32, TimerCallback)>,
+    next_timer_id: u64
+}
+
+impl Epoll {
+    fn add_interest(&self, fd: i32, events: u32, data: u64) {
+        let mut ev = libc::epoll_event {
+            events: events,
+            u64: data
+        };
+
+        unsafe {
+            libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_ADD, fd, &mut ev);
+        }
+    }
+
+    fn del_interest(&self, fd: i32) {
+        unsafe {
+            libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_DEL, fd, ptr::null_mut());
+        }
+    }
+}
+
+impl Reactor for Epoll {
+    type Notifier = EpollNotifier;
+
+    fn new() -> (Self, EpollNotifier) {
+        let epfd = unsafe { libc::epoll_create1(0) };
+        if epfd < 0 {
+            panic!("epoll_create1() failed");
+        }
+
+        let notify_fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK) };
+        if notify_fd < 0 {
+            panic!("eventfd() failed");
+        }
+
+        let epoll = Epoll {
+            epfd: epfd,
+            notify_fd: notify_fd,
+            reads: HashMap::new(),
+            writes: HashMap::new(),
+            timers: HashMap::new(),
+            next_timer_id: 0
+        };
+
+        epoll.add_interest(notify_fd, libc::EPOLLIN as u32, NOTIFY_TOKEN);
+
+        let notifier_fd = unsafe { libc::dup(notify_fd) };
+        if notifier_fd < 0 {
+            panic!("dup() failed");
+        }
+
+        (epoll, EpollNotifier { notify_fd: notifier_fd })
+    }
+
+    fn add_read_event(&mut self, fd: usize, event_type: ReadEventType) {
+        self.add_interest(fd as i32, libc::EPOLLIN as u32, fd as u64);
+        self.reads.insert(fd, event_type);
+    }
+
+    fn add_write_event(&mut self, fd: usize, event_type: WriteEventType) {
+        self.add_interest(fd as i32, libc::EPOLLOUT as u32, fd as u64);
+        self.writes.insert(fd, event_type);
+    }
+
+    fn add_timer(&mut self, callback: Box<dyn Fn() -> EventControl + Send>, delay: Duration) {
+        let id = TIMER_TOKEN_BASE + self.next_timer_id;
+        self.next_timer_id += 1;
+
+        let timer_fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK) };
+        if timer_fd < 0 {
+            panic!("timerfd_create() failed");
+        }
+
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: libc::timespec {
+                tv_sec: delay.as_secs() as libc::time_t,
+                tv_nsec: delay.subsec_nanos() as libc::c_long
+            }
+        };
+
+        unsafe {
+            libc::timerfd_settime(timer_fd, 0, &spec, ptr::null_mut());
+        }
+
+        self.add_interest(timer_fd, libc::EPOLLIN as u32, id);
+        self.timers.insert(id, (timer_fd, callback));
+    }
+
+    fn handle_events(&mut self, timeout: Option<Duration>) {
+        let mut events: [libc::epoll_event; 64] = unsafe { mem::zeroed() };
+
+        let timeout_ms = timeout.map_or(-1, |d| d.as_millis() as i32);
+
+        let n = unsafe {
+            libc::epoll_wait(self.epfd, events.as_mut_ptr(), events.len() as i32, timeout_ms)
+        };
+
+        if n < 0 {
+            return;
+        }
+
+        for ev in &events[0..n as usize] {
+            let token = ev.u64;
+
+            if token == NOTIFY_TOKEN {
+                let mut buf = [0u8; 8];
+                unsafe {
+                    libc::read(self.notify_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+                }
+                continue;
+            }
+
+            if token >= TIMER_TOKEN_BASE {
+                if let Some((timer_fd, callback)) = self.timers.remove(&token) {
+                    let mut buf = [0u8; 8];
+                    unsafe {
+                        libc::read(timer_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+                        libc::close(timer_fd);
+                    }
+                    callback();
+                }
+                continue;
+            }
+
+            let fd = token as usize;
+
+            if ev.events & (libc::EPOLLIN as u32) != 0 {
+                let control = {
+                    let event_type = match self.reads.get(&fd) {
+                        Some(e) => e,
+                        None => continue
+                    };
+
+                    match *event_type {
+                        ReadEventType::ACCEPT(ref cb) => {
+                            let mut listener = unsafe { TcpListener::from_raw_fd(fd as i32) };
+                            let control = cb(&mut listener);
+                            // Only the reactor holds this fd (it came from the
+                            // caller's into_raw_fd()), so on KEEP we must give
+                            // it back rather than let `listener`'s drop close
+                            // it out from under the still-live registration.
+                            if let EventControl::KEEP = control {
+                                mem::forget(listener);
+                            }
+                            control
+                        },
+                        ReadEventType::READ(ref cb) => {
+                            let mut stream = unsafe { TcpStream::from_raw_fd(fd as i32) };
+                            let control = cb(&mut stream);
+                            if let EventControl::KEEP = control {
+                                mem::forget(stream);
+                            }
+                            control
+                        },
+                        ReadEventType::RECV(ref cb) => {
+                            let socket = unsafe { UdpSocket::from_raw_fd(fd as i32) };
+                            let mut buf = [0u8; RECV_BUF_SIZE];
+                            let control = match socket.recv_from(&mut buf) {
+                                Ok((n, addr)) => cb(&buf[0..n], addr),
+                                Err(_) => EventControl::KEEP
+                            };
+                            if let EventControl::KEEP = control {
+                                mem::forget(socket);
+                            }
+                            control
+                        }
+                    }
+                };
+
+                if let EventControl::DELETE = control {
+                    self.del_interest(fd as i32);
+                    self.reads.remove(&fd);
+                }
+            } else if ev.events & (libc::EPOLLOUT as u32) != 0 {
+                let control = {
+                    let write = match self.writes.get(&fd) {
+                        Some(w) => w,
+                        None => continue
+                    };
+
+                    match *write {
+                        WriteEventType::WRITE(ref cb) => {
+                            let mut stream = unsafe { TcpStream::from_raw_fd(fd as i32) };
+                            let control = cb(&mut stream);
+                            if let EventControl::KEEP = control {
+                                mem::forget(stream);
+                            }
+                            control
+                        },
+                        WriteEventType::SEND(ref cb) => {
+                            let mut socket = unsafe { UdpSocket::from_raw_fd(fd as i32) };
+                            let control = cb(&mut socket);
+                            if let EventControl::KEEP = control {
+                                mem::forget(socket);
+                            }
+                            control
+                        }
+                    }
+                };
+
+                if let EventControl::DELETE = control {
+                    self.del_interest(fd as i32);
+                    self.writes.remove(&fd);
+                }
+            }
+        }
+    }
+
+}
+
+impl Drop for Epoll {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.notify_fd);
+            libc::close(self.epfd);
+        }
+    }
+}
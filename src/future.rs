@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+
+struct Shared {
+    done: bool,
+    waker: Option<Waker>
+}
+
+/// A handle to a result that some reactor callback will eventually produce
+/// (e.g. a write completing). Cloning a `Future` gives another handle to the
+/// same underlying completion state, so one half can be handed to the
+/// executor thread to mark done while the caller holds on to the other half
+/// and `.await`s it (or drives it manually with `block_on`).
+#[derive(Clone)]
+pub struct Future {
+    shared: Arc<Mutex<Shared>>
+}
+
+impl Default for Future {
+    fn default() -> Future {
+        Future::new()
+    }
+}
+
+impl Future {
+    pub fn new() -> Future {
+        Future {
+            shared: Arc::new(Mutex::new(Shared { done: false, waker: None }))
+        }
+    }
+
+    /// Mark this future's result as ready. Called from the executor thread
+    /// once the operation it represents (e.g. a write) has completed.
+    pub fn complete(&self) {
+        let waker = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.done = true;
+            shared.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.shared.lock().unwrap().done
+    }
+}
+
+impl ::std::future::Future for Future {
+    type Output = ();
+
+    fn poll(self: ::std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.done {
+            Poll::Ready(())
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drive `fut` to completion on the current thread, parking between
+/// wakeups rather than busy-polling, mirroring the reactor-driven
+/// single-threaded design of the executors themselves.
+pub fn block_on<F: ::std::future::Future>(fut: F) -> F::Output {
+    let mut fut = Box::pin(fut);
+    let waker: Waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match fut.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::future::Future as _;
+
+    fn noop_cx() -> Context<'static> {
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+            fn wake_by_ref(self: &Arc<Self>) {}
+        }
+
+        let waker: &'static Waker = Box::leak(Box::new(Arc::new(NoopWaker).into()));
+        Context::from_waker(waker)
+    }
+
+    #[test]
+    fn poll_ready_after_complete() {
+        let fut = Future::new();
+        fut.complete();
+
+        assert!(fut.is_done());
+        assert_eq!(Pin::new(&mut fut.clone()).poll(&mut noop_cx()), Poll::Ready(()));
+    }
+
+    #[test]
+    fn poll_pending_before_complete() {
+        let mut fut = Future::new();
+
+        assert!(!fut.is_done());
+        assert_eq!(Pin::new(&mut fut).poll(&mut noop_cx()), Poll::Pending);
+    }
+
+    #[test]
+    fn complete_wakes_a_pending_poll() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        struct FlagWaker(AtomicBool);
+        impl Wake for FlagWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let flag = Arc::new(FlagWaker(AtomicBool::new(false)));
+        let waker: Waker = flag.clone().into();
+        let mut cx = Context::from_waker(&waker);
+
+        let fut = Future::new();
+        let mut polled = fut.clone();
+
+        assert_eq!(Pin::new(&mut polled).poll(&mut cx), Poll::Pending);
+        assert!(!flag.0.load(Ordering::SeqCst));
+
+        fut.complete();
+
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert_eq!(Pin::new(&mut polled).poll(&mut noop_cx()), Poll::Ready(()));
+    }
+
+    #[test]
+    fn block_on_returns_once_completed() {
+        let fut = Future::new();
+        let fut2 = fut.clone();
+
+        thread::spawn(move || {
+            thread::sleep(::std::time::Duration::from_millis(10));
+            fut2.complete();
+        });
+
+        block_on(fut);
+    }
+}
@@ -0,0 +1,249 @@
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::os::unix::io::FromRawFd;
+use std::time::Duration;
+use std::collections::HashMap;
+use std::mem;
+use std::ptr;
+
+use libc;
+
+use reactor::{Reactor, Notifier, ReadEventType, WriteEventType};
+use EventControl;
+
+const NOTIFY_IDENT: libc::uintptr_t = 0;
+const RECV_BUF_SIZE: usize = 64 * 1024;
+
+/// `Reactor` implementation for macOS/BSD built directly on `libc::kevent`.
+pub struct Kqueue {
+    kq: i32,
+    reads: HashMap<usize, ReadEventType>,
+    writes: HashMap<usize, WriteEventType>,
+    timers: HashMap<usize, Box<dyn Fn() -> EventControl + Send>>,
+    next_timer_id: usize
+}
+
+/// Cross-thread wakeup handle for a `Kqueue`, backed by a `dup`'d copy of
+/// its kqueue fd so it can trigger the `EVFILT_USER` event registered by
+/// the reactor without touching any of the reactor's own state.
+pub struct KqueueNotifier {
+    kq: i32
+}
+
+unsafe impl Send for KqueueNotifier {}
+unsafe impl Sync for KqueueNotifier {}
+
+impl Notifier for KqueueNotifier {
+    fn notify(&self) {
+        let mut kev: libc::kevent = unsafe { mem::zeroed() };
+        kev.ident = NOTIFY_IDENT;
+        kev.filter = libc::EVFILT_USER;
+        kev.fflags = libc::NOTE_TRIGGER;
+
+        unsafe {
+            libc::kevent(self.kq, &kev, 1, ptr::null_mut(), 0, ptr::null());
+        }
+    }
+}
+
+impl Drop for KqueueNotifier {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.kq);
+        }
+    }
+}
+
+impl Kqueue {
+    fn register(&self, ident: libc::uintptr_t, filter: i16, flags: u16) {
+        let mut kev: libc::kevent = unsafe { mem::zeroed() };
+        kev.ident = ident;
+        kev.filter = filter;
+        kev.flags = flags;
+
+        unsafe {
+            libc::kevent(self.kq, &kev, 1, ptr::null_mut(), 0, ptr::null());
+        }
+    }
+}
+
+impl Reactor for Kqueue {
+    type Notifier = KqueueNotifier;
+
+    fn new() -> (Self, KqueueNotifier) {
+        let kq = unsafe { libc::kqueue() };
+        if kq < 0 {
+            panic!("kqueue() failed");
+        }
+
+        let kqueue = Kqueue {
+            kq: kq,
+            reads: HashMap::new(),
+            writes: HashMap::new(),
+            timers: HashMap::new(),
+            next_timer_id: 0
+        };
+
+        kqueue.register(NOTIFY_IDENT, libc::EVFILT_USER, libc::EV_ADD | libc::EV_CLEAR);
+
+        let notifier_fd = unsafe { libc::dup(kq) };
+        if notifier_fd < 0 {
+            panic!("dup() failed");
+        }
+
+        (kqueue, KqueueNotifier { kq: notifier_fd })
+    }
+
+    fn add_read_event(&mut self, fd: usize, event_type: ReadEventType) {
+        self.register(fd as libc::uintptr_t, libc::EVFILT_READ, libc::EV_ADD);
+        self.reads.insert(fd, event_type);
+    }
+
+    fn add_write_event(&mut self, fd: usize, event_type: WriteEventType) {
+        self.register(fd as libc::uintptr_t, libc::EVFILT_WRITE, libc::EV_ADD);
+        self.writes.insert(fd, event_type);
+    }
+
+    fn add_timer(&mut self, callback: Box<dyn Fn() -> EventControl + Send>, delay: Duration) {
+        let id = self.next_timer_id;
+        self.next_timer_id += 1;
+
+        let millis = (delay.as_secs() * 1000) + (delay.subsec_nanos() / 1_000_000) as u64;
+        self.register(id as libc::uintptr_t, libc::EVFILT_TIMER, libc::EV_ADD | libc::EV_ONESHOT);
+
+        let mut kev: libc::kevent = unsafe { mem::zeroed() };
+        kev.ident = id as libc::uintptr_t;
+        kev.filter = libc::EVFILT_TIMER;
+        kev.flags = libc::EV_ADD | libc::EV_ONESHOT;
+        kev.data = millis as isize;
+        unsafe {
+            libc::kevent(self.kq, &kev, 1, ptr::null_mut(), 0, ptr::null());
+        }
+
+        self.timers.insert(id, callback);
+    }
+
+    fn handle_events(&mut self, timeout: Option<Duration>) {
+        let mut events: [libc::kevent; 64] = unsafe { mem::zeroed() };
+
+        let ts = timeout.map(|d| libc::timespec {
+            tv_sec: d.as_secs() as libc::time_t,
+            tv_nsec: d.subsec_nanos() as libc::c_long
+        });
+        let ts_ptr = ts.as_ref().map_or(ptr::null(), |ts| ts as *const libc::timespec);
+
+        let n = unsafe {
+            libc::kevent(self.kq, ptr::null(), 0, events.as_mut_ptr(), events.len() as i32, ts_ptr)
+        };
+
+        if n < 0 {
+            return;
+        }
+
+        for kev in &events[0..n as usize] {
+            match kev.filter {
+                libc::EVFILT_READ => {
+                    let fd = kev.ident as usize;
+                    let control = {
+                        let event_type = match self.reads.get(&fd) {
+                            Some(e) => e,
+                            None => continue
+                        };
+
+                        match *event_type {
+                            ReadEventType::ACCEPT(ref cb) => {
+                                let mut listener = unsafe { TcpListener::from_raw_fd(fd as i32) };
+                                let control = cb(&mut listener);
+                                // Only the reactor holds this fd (it came from
+                                // the caller's into_raw_fd()), so on KEEP we
+                                // must give it back rather than let
+                                // `listener`'s drop close it out from under
+                                // the still-live registration.
+                                if let EventControl::KEEP = control {
+                                    mem::forget(listener);
+                                }
+                                control
+                            },
+                            ReadEventType::READ(ref cb) => {
+                                let mut stream = unsafe { TcpStream::from_raw_fd(fd as i32) };
+                                let control = cb(&mut stream);
+                                if let EventControl::KEEP = control {
+                                    mem::forget(stream);
+                                }
+                                control
+                            },
+                            ReadEventType::RECV(ref cb) => {
+                                let socket = unsafe { UdpSocket::from_raw_fd(fd as i32) };
+                                let mut buf = [0u8; RECV_BUF_SIZE];
+                                let control = match socket.recv_from(&mut buf) {
+                                    Ok((n, addr)) => cb(&buf[0..n], addr),
+                                    Err(_) => EventControl::KEEP
+                                };
+                                if let EventControl::KEEP = control {
+                                    mem::forget(socket);
+                                }
+                                control
+                            }
+                        }
+                    };
+
+                    if let EventControl::DELETE = control {
+                        self.register(fd as libc::uintptr_t, libc::EVFILT_READ, libc::EV_DELETE);
+                        self.reads.remove(&fd);
+                    }
+                },
+                libc::EVFILT_WRITE => {
+                    let fd = kev.ident as usize;
+                    let control = {
+                        let write = match self.writes.get(&fd) {
+                            Some(w) => w,
+                            None => continue
+                        };
+
+                        match *write {
+                            WriteEventType::WRITE(ref cb) => {
+                                let mut stream = unsafe { TcpStream::from_raw_fd(fd as i32) };
+                                let control = cb(&mut stream);
+                                if let EventControl::KEEP = control {
+                                    mem::forget(stream);
+                                }
+                                control
+                            },
+                            WriteEventType::SEND(ref cb) => {
+                                let mut socket = unsafe { UdpSocket::from_raw_fd(fd as i32) };
+                                let control = cb(&mut socket);
+                                if let EventControl::KEEP = control {
+                                    mem::forget(socket);
+                                }
+                                control
+                            }
+                        }
+                    };
+
+                    if let EventControl::DELETE = control {
+                        self.register(fd as libc::uintptr_t, libc::EVFILT_WRITE, libc::EV_DELETE);
+                        self.writes.remove(&fd);
+                    }
+                },
+                libc::EVFILT_TIMER => {
+                    let id = kev.ident as usize;
+                    if let Some(cb) = self.timers.remove(&id) {
+                        cb();
+                    }
+                },
+                libc::EVFILT_USER => {
+                    // Just a wakeup; nothing to dispatch.
+                },
+                _ => {}
+            }
+        }
+    }
+
+}
+
+impl Drop for Kqueue {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.kq);
+        }
+    }
+}
@@ -1,30 +1,45 @@
 extern crate libc;
-#[macro_use]
-extern crate log;
 
 pub mod future;
+mod reactor;
+mod multi_threaded_executor;
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
 mod kqueue;
+#[cfg(target_os = "linux")]
+mod epoll;
 
-use std::net::{TcpListener, TcpStream};
+use std::net::{TcpListener, TcpStream, UdpSocket, SocketAddr};
 use std::sync::mpsc::{channel, Sender, Receiver};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::sync::{Arc, Mutex, Condvar};
 use std::thread::JoinHandle;
 use std::thread;
+use std::io;
 use std::io::prelude::*;
+use std::io::ErrorKind;
 use std::os::unix::io::IntoRawFd;
-use std::os::unix::io::FromRawFd;
-use std::collections::VecDeque;
 use future::Future;
-use kqueue::{Kqueue, ReadEventType};
+use reactor::{ReadEventType, WriteEventType, RecvCallback, Notifier};
+pub use reactor::Reactor;
+pub use multi_threaded_executor::MultiThreadedExecutor;
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+pub use kqueue::Kqueue as DefaultReactor;
+#[cfg(target_os = "linux")]
+pub use epoll::Epoll as DefaultReactor;
 
 pub trait AsyncTcpListener {
-    fn accept_async<'a, F, T: Executor>(&self, event_loop: &'a T, accept_cb: F) where F: Fn(&mut TcpListener) -> EventControl + Send + 'a;
+    fn accept_async<F, T: Executor>(&self, event_loop: &T, accept_cb: F) where F: Fn(&mut TcpListener) -> EventControl + Send + 'static;
 }
 
 pub trait AsyncTcpStream {
-    fn read_async<'a, F, T: Executor>(&self, event_loop: &'a T, read_cb: F) where F: Fn(&mut TcpStream) -> EventControl + Send + 'a;
-    fn write_async<'a, T: Executor>(&self, event_loop: &'a T, data: Vec<u8>) -> Future;
+    fn read_async<F, T: Executor>(&self, event_loop: &T, read_cb: F) where F: Fn(&mut TcpStream) -> EventControl + Send + 'static;
+    fn write_async<T: Executor>(&self, event_loop: &T, data: Vec<u8>) -> Future;
+}
+
+pub trait AsyncUdpSocket {
+    fn recv_from_async<F, T: Executor>(&self, event_loop: &T, recv_cb: F) where F: Fn(&[u8], SocketAddr) -> EventControl + Send + 'static;
+    fn send_to_async<T: Executor>(&self, event_loop: &T, data: Vec<u8>, addr: SocketAddr) -> Future;
 }
 
 pub enum EventControl {
@@ -34,21 +49,39 @@ pub enum EventControl {
 
 pub trait Executor : Drop {
     fn new(name: &str) -> Self;
+
+    /// Like `new`, but instead of waking the reactor on every single
+    /// registration or `execute()` call, batch them: block in up to
+    /// `quantum`-sized slices and only act on whatever accumulated once a
+    /// slice elapses. Trades added latency (bounded by `quantum`) for far
+    /// fewer wakeups/syscalls under high rates of registrations/`execute()`
+    /// submissions.
+    ///
+    /// This does *not* batch dispatch of ready I/O callbacks: the reactor's
+    /// wait syscall still returns (and callbacks still run) as soon as the
+    /// first fd becomes ready, same as untrottled. Only the wakeup fast-path
+    /// for new registrations/`execute()` is suppressed until the quantum
+    /// boundary.
+    fn new_throttled(name: &str, quantum: Duration) -> Self;
+
     fn execute<F: Fn() + Send + 'static>(&self, callback: F);
     fn schedule<F: Fn() -> EventControl + Send + 'static>(&self, callback: F, delay: Duration) -> Future;
 
     fn shutdown(&self);
     fn join(&mut self);
 
-    fn accept<F: Fn(&mut TcpListener) -> EventControl + Send>(&self, listener: TcpListener, callback: F);
-    fn read<F: Fn(&mut TcpStream) -> EventControl + Send>(&self, stream: TcpStream, callback: F);
+    fn accept<F: Fn(&mut TcpListener) -> EventControl + Send + 'static>(&self, listener: TcpListener, callback: F);
+    fn read<F: Fn(&mut TcpStream) -> EventControl + Send + 'static>(&self, stream: TcpStream, callback: F);
     fn write(&self, stream: TcpStream, data: Vec<u8>) -> Future;
 
+    fn recv_from<F: Fn(&[u8], SocketAddr) -> EventControl + Send + 'static>(&self, socket: UdpSocket, callback: F);
+    fn send_to(&self, socket: UdpSocket, data: Vec<u8>, addr: SocketAddr) -> Future;
+
     fn notify(&self);
 }
 
 impl AsyncTcpListener for TcpListener {
-    fn accept_async<'a, F, T: Executor>(&self, event_loop: &'a T, accept_cb: F) where F: Fn(&mut TcpListener) -> EventControl + Send + 'a {
+    fn accept_async<F, T: Executor>(&self, event_loop: &T, accept_cb: F) where F: Fn(&mut TcpListener) -> EventControl + Send + 'static {
         self.set_nonblocking(true).unwrap();
 
         event_loop.accept(self.try_clone().unwrap(), accept_cb);
@@ -56,66 +89,91 @@ impl AsyncTcpListener for TcpListener {
 }
 
 impl AsyncTcpStream for TcpStream {
-    fn read_async<'a, F, T: Executor>(&self, event_loop: &'a T, read_cb: F) where F: Fn(&mut TcpStream) -> EventControl + Send + 'a {
+    fn read_async<F, T: Executor>(&self, event_loop: &T, read_cb: F) where F: Fn(&mut TcpStream) -> EventControl + Send + 'static {
         self.set_nonblocking(true).unwrap();
 
         event_loop.read(self.try_clone().unwrap(), read_cb);
     }
 
-    fn write_async<'a, T: Executor>(&self, event_loop: &'a T, data: Vec<u8>) -> Future {
+    fn write_async<T: Executor>(&self, event_loop: &T, data: Vec<u8>) -> Future {
         self.set_nonblocking(true).unwrap();
 
         event_loop.write(self.try_clone().unwrap(), data)
     }
 }
 
+impl AsyncUdpSocket for UdpSocket {
+    fn recv_from_async<F, T: Executor>(&self, event_loop: &T, recv_cb: F) where F: Fn(&[u8], SocketAddr) -> EventControl + Send + 'static {
+        self.set_nonblocking(true).unwrap();
+
+        event_loop.recv_from(self.try_clone().unwrap(), recv_cb);
+    }
+
+    fn send_to_async<T: Executor>(&self, event_loop: &T, data: Vec<u8>, addr: SocketAddr) -> Future {
+        self.set_nonblocking(true).unwrap();
+
+        event_loop.send_to(self.try_clone().unwrap(), data, addr)
+    }
+}
+
 enum ThreadMessage {
     Shutdown,
     Execute {
-        callback: Box<Fn() + Send>
+        callback: Box<dyn Fn() + Send>
     },
     Schedule {
         delay: Duration,
-        callback: Box<Fn() -> EventControl + Send>
+        callback: Box<dyn Fn() -> EventControl + Send>
     },
     AddAcceptEvent {
         fd: i32,
-        callback: Box<Fn(&mut TcpListener) -> EventControl + Send>
+        callback: Box<dyn Fn(&mut TcpListener) -> EventControl + Send>
     },
     AddReadEvent {
         fd: i32,
-        callback: Box<Fn(&mut TcpStream) -> EventControl + Send>
+        callback: Box<dyn Fn(&mut TcpStream) -> EventControl + Send>
     },
     AddWriteEvent {
         fd: i32,
         payload: Vec<u8>,
         future: Future
+    },
+    AddRecvEvent {
+        fd: i32,
+        callback: RecvCallback
+    },
+    AddSendEvent {
+        fd: i32,
+        payload: Vec<u8>,
+        addr: SocketAddr,
+        future: Future
     }
 }
 
-pub struct SingleThreadedExecutor {
+pub struct SingleThreadedExecutor<R: Reactor + Send + 'static = DefaultReactor> {
     join_handle: Mutex<Option<JoinHandle<()>>>,
-    kq: Kqueue,
-    sender: Mutex<Sender<ThreadMessage>>
+    notifier: R::Notifier,
+    sender: Mutex<Sender<ThreadMessage>>,
+    throttled: bool
 }
 
-impl Executor for SingleThreadedExecutor {
-    fn new(name: &str) -> Self {
-
+impl<R: Reactor + Send + 'static> SingleThreadedExecutor<R> {
+    fn spawn(name: &str, quantum: Option<Duration>) -> Self {
         let (tx, rx): (Sender<ThreadMessage>, Receiver<ThreadMessage>) = channel();
         let pair = Arc::new((Mutex::new(false), Condvar::new()));
+        let (kq, notifier) = R::new();
 
         let pair2 = pair.clone();
-        let mut tmp = Kqueue::new();
         let x = SingleThreadedExecutor {
             sender: Mutex::new(tx),
-            kq: tmp,
+            notifier: notifier,
+            throttled: quantum.is_some(),
             join_handle: Mutex::new(Some(thread::Builder::new().name(name.to_string()).spawn( move || {
-                executor_loop(tmp, rx, &*pair2); //Get this to work again
+                executor_loop(kq, rx, &pair2, quantum);
             }).unwrap()))
         };
 
-        let &(ref lock, ref cvar) = &*pair;
+        let (lock, cvar) = &*pair;
         let mut started = lock.lock().unwrap();
         while !*started {
             started = cvar.wait(started).unwrap();
@@ -124,13 +182,32 @@ impl Executor for SingleThreadedExecutor {
         x
     }
 
+    /// Notify the reactor unless throttling is active, in which case
+    /// registrations and submissions are left to be picked up at the next
+    /// quantum boundary instead of interrupting the wait immediately.
+    fn notify_unless_throttled(&self) {
+        if !self.throttled {
+            self.notify();
+        }
+    }
+}
+
+impl<R: Reactor + Send + 'static> Executor for SingleThreadedExecutor<R> {
+    fn new(name: &str) -> Self {
+        Self::spawn(name, None)
+    }
+
+    fn new_throttled(name: &str, quantum: Duration) -> Self {
+        Self::spawn(name, Some(quantum))
+    }
+
     fn execute<F: Fn() + Send + 'static>(&self, callback: F) {
         let s = self.sender.lock().unwrap();
         s.send(ThreadMessage::Execute {
             callback: Box::new(callback)
         }).unwrap();
 
-        self.notify();
+        self.notify_unless_throttled();
     }
 
     fn schedule<F: Fn() -> EventControl + Send + 'static>(&self, callback: F, delay: Duration) -> Future {
@@ -140,7 +217,7 @@ impl Executor for SingleThreadedExecutor {
             callback: Box::new(callback)
         }).unwrap();
 
-        self.notify();
+        self.notify_unless_throttled();
         Future::new()
     }
 
@@ -151,7 +228,7 @@ impl Executor for SingleThreadedExecutor {
             callback: Box::new(callback)
         }).unwrap();
 
-        self.notify();
+        self.notify_unless_throttled();
     }
 
     fn read<F: Fn(&mut TcpStream) -> EventControl + Send + 'static>(&self, stream: TcpStream, callback: F) {
@@ -161,7 +238,7 @@ impl Executor for SingleThreadedExecutor {
             callback: Box::new(callback)
         }).unwrap();
 
-        self.notify();
+        self.notify_unless_throttled();
     }
 
     fn write(&self, stream: TcpStream, data: Vec<u8>) -> Future {
@@ -174,6 +251,32 @@ impl Executor for SingleThreadedExecutor {
             future: fut1
         }).unwrap();
 
+        self.notify_unless_throttled();
+        future
+    }
+
+    fn recv_from<F: Fn(&[u8], SocketAddr) -> EventControl + Send + 'static>(&self, socket: UdpSocket, callback: F) {
+        let s = self.sender.lock().unwrap();
+        s.send(ThreadMessage::AddRecvEvent {
+            fd: socket.into_raw_fd(),
+            callback: Box::new(callback)
+        }).unwrap();
+
+        self.notify_unless_throttled();
+    }
+
+    fn send_to(&self, socket: UdpSocket, data: Vec<u8>, addr: SocketAddr) -> Future {
+        let s = self.sender.lock().unwrap();
+        let future = Future::new();
+        let fut1 = future.clone();
+        s.send(ThreadMessage::AddSendEvent {
+            fd: socket.into_raw_fd(),
+            payload: data,
+            addr: addr,
+            future: fut1
+        }).unwrap();
+
+        self.notify_unless_throttled();
         future
     }
 
@@ -188,7 +291,7 @@ impl Executor for SingleThreadedExecutor {
     }
 
     fn notify(&self) {
-        self.kq.notify();
+        self.notifier.notify();
     }
 
     fn join(&mut self) {
@@ -199,29 +302,26 @@ impl Executor for SingleThreadedExecutor {
     }
 }
 
-impl Drop for SingleThreadedExecutor {
+impl<R: Reactor + Send + 'static> Drop for SingleThreadedExecutor<R> {
     fn drop(&mut self) {
         self.shutdown();
     }
 }
 
-enum CallbackType {
-    ACCEPT(Box<Fn(&mut TcpListener) -> EventControl>),
-    READ(Box<Fn(&mut TcpStream) -> EventControl>)
-}
-
-fn executor_loop(mut kq: Kqueue, receiver: Receiver<ThreadMessage>, pair: &(Mutex<bool>, Condvar)) {
-    let &(ref lock, ref cvar) = pair;
+fn executor_loop<R: Reactor>(mut kq: R, receiver: Receiver<ThreadMessage>, pair: &(Mutex<bool>, Condvar), quantum: Option<Duration>) {
+    let (lock, cvar) = pair;
     {
         let mut started = lock.lock().unwrap();
         *started = true;
     }
     cvar.notify_one();
 
+    let mut quantum_start = Instant::now();
+
     loop {
         loop {
             match receiver.try_recv() { //This should be registered with  kevent too
-                Ok(ThreadMessage::Shutdown)        => break,
+                Ok(ThreadMessage::Shutdown)        => return,
                 Ok(ThreadMessage::AddAcceptEvent{ fd, callback }) => {
                     kq.add_read_event(fd as usize, ReadEventType::ACCEPT(callback));
                 },
@@ -229,9 +329,13 @@ fn executor_loop(mut kq: Kqueue, receiver: Receiver<ThreadMessage>, pair: &(Mute
                     kq.add_read_event(fd as usize, ReadEventType::READ(callback));
                 },
                 Ok(ThreadMessage::AddWriteEvent{ fd, payload, future }) => {
-                    kq.add_write_event(fd as usize, Box::new(|s| {
-                        EventControl::KEEP
-                    })); //TODO: add real write event
+                    kq.add_write_event(fd as usize, WriteEventType::WRITE(write_event_callback(payload, future)));
+                },
+                Ok(ThreadMessage::AddRecvEvent{ fd, callback }) => {
+                    kq.add_read_event(fd as usize, ReadEventType::RECV(callback));
+                },
+                Ok(ThreadMessage::AddSendEvent{ fd, payload, addr, future }) => {
+                    kq.add_write_event(fd as usize, WriteEventType::SEND(send_event_callback(payload, addr, future)));
                 },
                 Ok(ThreadMessage::Execute{ callback }) => {
                     callback();
@@ -245,7 +349,126 @@ fn executor_loop(mut kq: Kqueue, receiver: Receiver<ThreadMessage>, pair: &(Mute
             }
         }
 
-       kq.handle_events();
+        match quantum {
+            Some(quantum) => {
+                let elapsed = quantum_start.elapsed();
+                if elapsed >= quantum {
+                    quantum_start = Instant::now();
+                } else {
+                    kq.handle_events(Some(quantum - elapsed));
+                    if quantum_start.elapsed() >= quantum {
+                        quantum_start = Instant::now();
+                    }
+                }
+            },
+            None => kq.handle_events(None)
+        }
+    }
+}
+
+/// Build the write-readiness callback for `AddWriteEvent`: flushes as much
+/// of `payload` as the socket will currently accept, keeping the write
+/// registered across `WouldBlock` and resolving `future` once it's all
+/// been written or the write has failed. Shared by every executor that drives an `AddWriteEvent`
+/// through its reactor.
+fn write_event_callback(payload: Vec<u8>, future: Future) -> Box<dyn Fn(&mut TcpStream) -> EventControl + Send> {
+    let remaining = Mutex::new(payload);
+
+    Box::new(move |stream| {
+        let mut remaining = remaining.lock().unwrap();
+
+        loop {
+            if remaining.is_empty() {
+                future.complete();
+                return EventControl::DELETE;
+            }
+
+            // A 0-byte write with a non-empty buffer means no progress was
+            // made (e.g. the peer closed its read side); treat it like
+            // `std::io::Write::write_all` does and fail the same way a hard
+            // I/O error would, rather than retrying forever. On failure
+            // `future` completes just as on success (it carries no error)
+            // and the unsent tail is discarded.
+            let result = match stream.write(&remaining) {
+                Ok(0) => Err(io::Error::from(ErrorKind::WriteZero)),
+                other => other
+            };
+
+            match result {
+                Ok(n) => {
+                    remaining.drain(0..n);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    return EventControl::KEEP;
+                },
+                Err(_) => {
+                    future.complete();
+                    return EventControl::DELETE;
+                }
+            }
+        }
+    })
+}
+
+/// Build the send-readiness callback for `AddSendEvent`: a datagram send is
+/// atomic from the caller's point of view, so unlike `write_event_callback`
+/// there's no partial-progress state to track — either the whole `payload`
+/// goes out in one `send_to()` or, on `WouldBlock`, the registration stays
+/// live and the send is retried on the next writable event. Any other error
+/// completes `future` and drops the datagram.
+fn send_event_callback(payload: Vec<u8>, addr: SocketAddr, future: Future) -> Box<dyn Fn(&mut UdpSocket) -> EventControl + Send> {
+    Box::new(move |socket| {
+        match socket.send_to(&payload, addr) {
+            Ok(_) => {
+                future.complete();
+                EventControl::DELETE
+            },
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                EventControl::KEEP
+            },
+            Err(_) => {
+                future.complete();
+                EventControl::DELETE
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_event_callback_completes_once_payload_is_flushed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let future = Future::new();
+        let callback = write_event_callback(b"hello".to_vec(), future.clone());
+
+        assert!(matches!(callback(&mut client), EventControl::DELETE));
+        assert!(future.is_done());
+
+        let mut received = [0u8; 5];
+        server.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello");
+    }
+
+    #[test]
+    fn send_event_callback_completes_after_one_send() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let future = Future::new();
+        let callback = send_event_callback(b"ping".to_vec(), server.local_addr().unwrap(), future.clone());
+
+        assert!(matches!(callback(&mut client), EventControl::DELETE));
+        assert!(future.is_done());
+
+        let mut received = [0u8; 4];
+        server.recv(&mut received).unwrap();
+        assert_eq!(&received, b"ping");
     }
 }
 
@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::net::{TcpListener, TcpStream, UdpSocket, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use std::os::unix::io::IntoRawFd;
+
+use {DefaultReactor, EventControl, Executor, ThreadMessage};
+use future::Future;
+use reactor::{Reactor, Notifier, ReadEventType, WriteEventType};
+use write_event_callback;
+use send_event_callback;
+
+type Task = Box<dyn Fn() + Send>;
+type TaskDeque = Mutex<VecDeque<Task>>;
+
+/// The state shared by every worker for the injector-queue/work-stealing
+/// scheduler: the global injector, each worker's local deque, and every
+/// worker's notifier (needed so `next_task` can wake a sibling it just
+/// handed overflow work to).
+struct Shared<R: Reactor> {
+    injector: Arc<TaskDeque>,
+    locals: Arc<Vec<TaskDeque>>,
+    notifiers: Arc<Vec<R::Notifier>>
+}
+
+impl<R: Reactor> Clone for Shared<R> {
+    fn clone(&self) -> Self {
+        Shared {
+            injector: self.injector.clone(),
+            locals: self.locals.clone(),
+            notifiers: self.notifiers.clone()
+        }
+    }
+}
+
+struct WorkerHandle<R: Reactor> {
+    sender: Sender<ThreadMessage>,
+    join_handle: Mutex<Option<JoinHandle<()>>>,
+    _reactor: PhantomData<R>
+}
+
+/// Work-stealing counterpart to `SingleThreadedExecutor`.
+///
+/// Each worker owns its own reactor and drains its own `ThreadMessage`
+/// channel. Every `accept`/`read`/`write`/`recv_from`/`send_to` call takes a
+/// fresh fd (via `into_raw_fd()`), so there's never a second registration to
+/// keep consistent with the first; registrations are simply spread round-robin
+/// across workers. Plain `execute()`/`schedule()` submissions go through a
+/// global injector queue; idle workers pull a batch from it (keeping one task
+/// for themselves, spreading the rest round-robin across the local deques,
+/// starting with the next sibling's) and, failing that, steal a task from a sibling's local deque.
+pub struct MultiThreadedExecutor<R: Reactor + Send + 'static = DefaultReactor> {
+    workers: Vec<WorkerHandle<R>>,
+    notifiers: Arc<Vec<R::Notifier>>,
+    injector: Arc<TaskDeque>,
+    next_worker: AtomicUsize,
+    throttled: bool
+}
+
+impl<R: Reactor + Send + 'static> MultiThreadedExecutor<R> {
+    fn spawn(name: &str, quantum: Option<Duration>) -> Self {
+        let worker_count = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+        let injector = Arc::new(Mutex::new(VecDeque::new()));
+        let locals: Arc<Vec<TaskDeque>> = Arc::new((0..worker_count).map(|_| Mutex::new(VecDeque::new())).collect());
+        let pair = Arc::new((Mutex::new(0), Condvar::new()));
+
+        let (kqs, notifiers): (Vec<R>, Vec<R::Notifier>) = (0..worker_count).map(|_| R::new()).unzip();
+        let shared = Shared {
+            injector: injector.clone(),
+            locals: locals,
+            notifiers: Arc::new(notifiers)
+        };
+
+        let workers = kqs.into_iter().enumerate().map(|(index, kq)| {
+            let (tx, rx): (Sender<ThreadMessage>, Receiver<ThreadMessage>) = channel();
+            let shared = shared.clone();
+            let pair2 = pair.clone();
+
+            WorkerHandle {
+                sender: tx,
+                join_handle: Mutex::new(Some(thread::Builder::new().name(format!("{}-{}", name, index)).spawn(move || {
+                    worker_loop(kq, rx, shared, index, &pair2, quantum);
+                }).unwrap())),
+                _reactor: PhantomData
+            }
+        }).collect::<Vec<_>>();
+
+        let (lock, cvar) = &*pair;
+        let mut started = lock.lock().unwrap();
+        while *started < worker_count {
+            started = cvar.wait(started).unwrap();
+        }
+
+        MultiThreadedExecutor {
+            workers: workers,
+            notifiers: shared.notifiers,
+            injector: injector,
+            next_worker: AtomicUsize::new(0),
+            throttled: quantum.is_some()
+        }
+    }
+
+    fn pick_worker(&self) -> usize {
+        self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len()
+    }
+
+    fn send_to(&self, index: usize, message: ThreadMessage) {
+        self.workers[index].sender.send(message).unwrap();
+        if !self.throttled {
+            self.notifiers[index].notify();
+        }
+    }
+}
+
+impl<R: Reactor + Send + 'static> Executor for MultiThreadedExecutor<R> {
+    fn new(name: &str) -> Self {
+        Self::spawn(name, None)
+    }
+
+    fn new_throttled(name: &str, quantum: Duration) -> Self {
+        Self::spawn(name, Some(quantum))
+    }
+
+    fn execute<F: Fn() + Send + 'static>(&self, callback: F) {
+        self.injector.lock().unwrap().push_back(Box::new(callback));
+
+        if !self.throttled {
+            let index = self.pick_worker();
+            self.notifiers[index].notify();
+        }
+    }
+
+    fn schedule<F: Fn() -> EventControl + Send + 'static>(&self, callback: F, delay: Duration) -> Future {
+        let index = self.pick_worker();
+        self.send_to(index, ThreadMessage::Schedule {
+            delay: delay,
+            callback: Box::new(callback)
+        });
+
+        Future::new()
+    }
+
+    fn accept<F: Fn(&mut TcpListener) -> EventControl + Send + 'static>(&self, listener: TcpListener, callback: F) {
+        let fd = listener.into_raw_fd();
+        let index = self.pick_worker();
+        self.send_to(index, ThreadMessage::AddAcceptEvent {
+            fd: fd,
+            callback: Box::new(callback)
+        });
+    }
+
+    fn read<F: Fn(&mut TcpStream) -> EventControl + Send + 'static>(&self, stream: TcpStream, callback: F) {
+        let fd = stream.into_raw_fd();
+        let index = self.pick_worker();
+        self.send_to(index, ThreadMessage::AddReadEvent {
+            fd: fd,
+            callback: Box::new(callback)
+        });
+    }
+
+    fn write(&self, stream: TcpStream, data: Vec<u8>) -> Future {
+        let fd = stream.into_raw_fd();
+        let index = self.pick_worker();
+        let future = Future::new();
+        let fut1 = future.clone();
+
+        self.send_to(index, ThreadMessage::AddWriteEvent {
+            fd: fd,
+            payload: data,
+            future: fut1
+        });
+
+        future
+    }
+
+    fn recv_from<F: Fn(&[u8], SocketAddr) -> EventControl + Send + 'static>(&self, socket: UdpSocket, callback: F) {
+        let fd = socket.into_raw_fd();
+        let index = self.pick_worker();
+        self.send_to(index, ThreadMessage::AddRecvEvent {
+            fd: fd,
+            callback: Box::new(callback)
+        });
+    }
+
+    fn send_to(&self, socket: UdpSocket, data: Vec<u8>, addr: SocketAddr) -> Future {
+        let fd = socket.into_raw_fd();
+        let index = self.pick_worker();
+        let future = Future::new();
+        let fut1 = future.clone();
+
+        self.send_to(index, ThreadMessage::AddSendEvent {
+            fd: fd,
+            payload: data,
+            addr: addr,
+            future: fut1
+        });
+
+        future
+    }
+
+    fn shutdown(&self) {
+        for (worker, notifier) in self.workers.iter().zip(self.notifiers.iter()) {
+            match worker.sender.send(ThreadMessage::Shutdown) {
+                Ok(()) => {},
+                Err(e) => println!("Error occurred!! {}", e)
+            }
+            notifier.notify();
+        }
+    }
+
+    fn notify(&self) {
+        for notifier in self.notifiers.iter() {
+            notifier.notify();
+        }
+    }
+
+    fn join(&mut self) {
+        for worker in &self.workers {
+            let mut handle = worker.join_handle.lock().unwrap();
+            if let Some(x) = handle.take() {
+                x.join().unwrap();
+            }
+        }
+    }
+}
+
+impl<R: Reactor + Send + 'static> Drop for MultiThreadedExecutor<R> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn steal(locals: &[TaskDeque], index: usize) -> Option<Task> {
+    for (i, local) in locals.iter().enumerate() {
+        if i == index {
+            continue;
+        }
+
+        if let Some(task) = local.lock().unwrap().pop_back() {
+            return Some(task);
+        }
+    }
+
+    None
+}
+
+fn next_task<R: Reactor>(shared: &Shared<R>, index: usize, throttled: bool) -> Option<Task> {
+    let locals = &shared.locals;
+
+    if let Some(task) = locals[index].lock().unwrap().pop_front() {
+        return Some(task);
+    }
+
+    {
+        let mut injector = shared.injector.lock().unwrap();
+        if let Some(first) = injector.pop_front() {
+            // Spread the rest of the batch across the local deques
+            // round-robin, starting with the next worker rather than our
+            // own, so they're immediately visible to `steal` instead of
+            // sitting behind everything we're about to run ourselves. The
+            // round-robin wraps, so our own deque also gets tasks when the
+            // batch outnumbers our siblings (or there is only one worker).
+            // Unless throttled, wake each worker we hand work to, or it may
+            // sit blocked in handle_events without ever noticing the
+            // stealable task we just gave it.
+            let mut next = (index + 1) % locals.len();
+            let mut woke = vec![false; locals.len()];
+            while let Some(task) = injector.pop_front() {
+                locals[next].lock().unwrap().push_back(task);
+                woke[next] = true;
+                next = (next + 1) % locals.len();
+            }
+            if !throttled {
+                for (i, notifier) in shared.notifiers.iter().enumerate() {
+                    if woke[i] {
+                        notifier.notify();
+                    }
+                }
+            }
+            return Some(first);
+        }
+    }
+
+    steal(locals, index)
+}
+
+fn worker_loop<R: Reactor>(mut kq: R, receiver: Receiver<ThreadMessage>, shared: Shared<R>, index: usize, pair: &(Mutex<usize>, Condvar), quantum: Option<Duration>) {
+    let (lock, cvar) = pair;
+    {
+        let mut started = lock.lock().unwrap();
+        *started += 1;
+    }
+    cvar.notify_all();
+
+    let mut quantum_start = Instant::now();
+
+    'outer: loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(ThreadMessage::Shutdown) => break 'outer,
+                Ok(ThreadMessage::AddAcceptEvent{ fd, callback }) => {
+                    kq.add_read_event(fd as usize, ReadEventType::ACCEPT(callback));
+                },
+                Ok(ThreadMessage::AddReadEvent{ fd, callback }) => {
+                    kq.add_read_event(fd as usize, ReadEventType::READ(callback));
+                },
+                Ok(ThreadMessage::AddWriteEvent{ fd, payload, future }) => {
+                    kq.add_write_event(fd as usize, WriteEventType::WRITE(write_event_callback(payload, future)));
+                },
+                Ok(ThreadMessage::AddRecvEvent{ fd, callback }) => {
+                    kq.add_read_event(fd as usize, ReadEventType::RECV(callback));
+                },
+                Ok(ThreadMessage::AddSendEvent{ fd, payload, addr, future }) => {
+                    kq.add_write_event(fd as usize, WriteEventType::SEND(send_event_callback(payload, addr, future)));
+                },
+                Ok(ThreadMessage::Execute{ callback }) => {
+                    callback();
+                },
+                Ok(ThreadMessage::Schedule{ delay, callback }) => {
+                    kq.add_timer(callback, delay);
+                },
+                Err(_) => {
+                    break;
+                }
+            }
+        }
+
+        if let Some(task) = next_task(&shared, index, quantum.is_some()) {
+            task();
+            continue;
+        }
+
+        match quantum {
+            Some(quantum) => {
+                let elapsed = quantum_start.elapsed();
+                if elapsed >= quantum {
+                    quantum_start = Instant::now();
+                } else {
+                    kq.handle_events(Some(quantum - elapsed));
+                    if quantum_start.elapsed() >= quantum {
+                        quantum_start = Instant::now();
+                    }
+                }
+            },
+            None => kq.handle_events(None)
+        }
+    }
+}
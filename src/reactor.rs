@@ -0,0 +1,65 @@
+use std::net::{TcpListener, TcpStream, UdpSocket, SocketAddr};
+use std::time::Duration;
+
+use EventControl;
+
+/// Callback for a `RECV` registration: the reactor hands it the received
+/// datagram's bytes and source address directly, rather than the socket.
+pub type RecvCallback = Box<dyn Fn(&[u8], SocketAddr) -> EventControl + Send>;
+
+/// The kind of readiness a read-side registration should be dispatched as.
+///
+/// `ACCEPT` callbacks are invoked with the listening socket, `READ` callbacks
+/// with the connected stream; both just get a chance to react to readability
+/// and return whether the registration should stay live. `RECV` is datagram
+/// oriented: the reactor itself performs the `recv_from` and hands the
+/// callback the received bytes and source address rather than the socket.
+pub enum ReadEventType {
+    ACCEPT(Box<dyn Fn(&mut TcpListener) -> EventControl + Send>),
+    READ(Box<dyn Fn(&mut TcpStream) -> EventControl + Send>),
+    RECV(RecvCallback)
+}
+
+/// The kind of readiness a write-side registration should be dispatched as.
+///
+/// `WRITE` drives a stream-oriented flush (tracking how much of a byte
+/// buffer has been written so far); `SEND` drives a single datagram send,
+/// which is either fully accepted by the kernel or not sent at all.
+pub enum WriteEventType {
+    WRITE(Box<dyn Fn(&mut TcpStream) -> EventControl + Send>),
+    SEND(Box<dyn Fn(&mut UdpSocket) -> EventControl + Send>)
+}
+
+/// A cheap handle that can wake a thread blocked in a `Reactor`'s
+/// `handle_events()` from any other thread.
+///
+/// Reactor state itself (registered callbacks, the epoll/kqueue fd) lives on
+/// the executor thread and isn't `Sync`; the notifier is the only piece that
+/// needs to cross thread boundaries, so it's split out as its own type.
+pub trait Notifier: Send + Sync {
+    fn notify(&self);
+}
+
+/// Platform event notification backend used by the executors.
+///
+/// `SingleThreadedExecutor` and `MultiThreadedExecutor` are generic over
+/// this trait so the same `ThreadMessage` pipeline and
+/// executor loop run unchanged on top of kqueue (macOS/BSD) or epoll
+/// (Linux). Implementations own the underlying fd and any bookkeeping
+/// needed to dispatch readiness back to the registered callbacks.
+pub trait Reactor: Sized {
+    type Notifier: Notifier + 'static;
+
+    /// Create the reactor along with a `Notifier` handle other threads can
+    /// use to interrupt a blocked `handle_events()` call.
+    fn new() -> (Self, Self::Notifier);
+
+    fn add_read_event(&mut self, fd: usize, event_type: ReadEventType);
+    fn add_write_event(&mut self, fd: usize, event_type: WriteEventType);
+    fn add_timer(&mut self, callback: Box<dyn Fn() -> EventControl + Send>, delay: Duration);
+
+    /// Block until at least one registered event is ready (or the
+    /// `Notifier` is triggered), dispatching every ready callback, or until
+    /// `timeout` elapses with nothing ready. `None` blocks indefinitely.
+    fn handle_events(&mut self, timeout: Option<Duration>);
+}